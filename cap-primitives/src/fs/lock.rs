@@ -0,0 +1,37 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::LockOptions;
+
+#[cfg(windows)]
+use crate::windows::fs::lock as imp;
+
+/// A held advisory lock, backing [`crate::fs::Dir::lock`]'s return value.
+/// Releases the lock by removing the lockfile on drop.
+#[cfg(windows)]
+pub use imp::LockGuard;
+
+/// On platforms other than Windows no lock can be acquired, so this type
+/// has no values and [`lock`] always returns an error.
+#[cfg(not(windows))]
+pub enum LockGuard {}
+
+/// Acquires an advisory lock on `path`, relative to the directory `start`
+/// is opened on, backing [`crate::fs::Dir::lock`]. This is the
+/// sandboxed, cross-platform entry point the `Dir` method is implemented
+/// in terms of; see [`LockOptions`] for the stale-lock-breaking knob.
+pub fn lock(start: &fs::File, path: &Path, options: &LockOptions) -> io::Result<LockGuard> {
+    #[cfg(windows)]
+    {
+        imp::acquire_lock_unchecked(start, path, options)
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = (start, path, options);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "lock is only implemented on Windows in this tree",
+        ))
+    }
+}
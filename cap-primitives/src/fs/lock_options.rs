@@ -0,0 +1,28 @@
+/// Options for [`crate::fs::Dir::lock`].
+///
+/// Mirrors the builder pattern used by [`crate::fs::OpenOptions`]: start
+/// from [`LockOptions::new`] and adjust the defaults that matter.
+#[derive(Debug, Clone)]
+pub struct LockOptions {
+    /// Whether to detect and break a stale lock (one recorded by this
+    /// host whose pid is no longer running) and retry acquisition once.
+    /// Defaults to `true`.
+    pub(crate) break_stale: bool,
+}
+
+impl LockOptions {
+    pub fn new() -> Self {
+        Self { break_stale: true }
+    }
+
+    pub fn break_stale(&mut self, break_stale: bool) -> &mut Self {
+        self.break_stale = break_stale;
+        self
+    }
+}
+
+impl Default for LockOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
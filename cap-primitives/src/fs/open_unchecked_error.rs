@@ -0,0 +1,39 @@
+use std::io;
+
+/// The kind of link that was encountered when a symlink-following check
+/// rejected a path.
+///
+/// Platforms such as Windows need to report additional detail about what
+/// sort of reparse point was found, since different kinds need different
+/// handling by callers (for example, a manual path-resolution loop needs
+/// to know whether a component is a symlink it should resolve relative to
+/// its parent, or a junction/mount point that redirects to an unrelated
+/// location).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SymlinkKind {
+    /// A symlink to a file.
+    File,
+
+    /// A symlink to a directory.
+    Dir,
+
+    /// A Windows directory junction or volume mount point. These are
+    /// reparse points that redirect to an arbitrary target the same way a
+    /// directory symlink does, but `std` doesn't classify them as
+    /// symlinks.
+    Junction,
+}
+
+/// The result of an unsandboxed open attempt, with the extra detail the
+/// sandboxed resolver needs that `io::Error` alone doesn't carry.
+pub(crate) enum OpenUncheckedError {
+    /// An error unrelated to symlinks or path resolution.
+    Other(io::Error),
+
+    /// The path, or a component of it, doesn't exist.
+    NotFound(io::Error),
+
+    /// The path names a symlink (or symlink-like reparse point) and the
+    /// caller asked not to follow it.
+    Symlink(io::Error, SymlinkKind),
+}
@@ -0,0 +1,32 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[cfg(windows)]
+use crate::windows::fs::rename_or_copy as imp;
+
+/// Moves `old_path` (relative to `old_start`) to `new_path` (relative to
+/// `new_start`), backing [`crate::fs::Dir::rename_or_copy`] and its
+/// [`crate::fs::Dir::move_file`] alias: tries an atomic rename first and,
+/// only when the two paths live on different devices, falls back to a
+/// sandboxed recursive copy followed by removing the source. Any other
+/// rename error is returned as-is without attempting the fallback.
+pub fn rename_or_copy(
+    old_start: &fs::File,
+    old_path: &Path,
+    new_start: &fs::File,
+    new_path: &Path,
+) -> io::Result<()> {
+    #[cfg(windows)]
+    {
+        imp::rename_or_copy_unchecked(old_start, old_path, new_start, new_path)
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = (old_start, old_path, new_start, new_path);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "rename_or_copy is only implemented on Windows in this tree",
+        ))
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[cfg(windows)]
+use crate::windows::fs::atomic_write as imp;
+
+/// A crash-safe writer opened by [`atomic_writer`], backing
+/// [`crate::fs::Dir::atomic_writer`]. Writes land in a temp file next to
+/// the destination and only replace it once [`Self::commit`] is called;
+/// dropping the writer without committing discards the temp file instead.
+#[cfg(windows)]
+pub use imp::AtomicFileWriter;
+
+/// On platforms other than Windows no writer can be opened, so this type
+/// has no values and [`atomic_writer`] always returns an error.
+#[cfg(not(windows))]
+pub enum AtomicFileWriter {}
+
+/// Opens a crash-safe writer for `dest_path`, relative to the directory
+/// `start` is opened on. This is the sandboxed, cross-platform entry
+/// point [`crate::fs::Dir::atomic_writer`] is implemented in terms of;
+/// see [`write_atomic`] for a one-shot convenience wrapper.
+pub fn atomic_writer(start: &fs::File, dest_path: &Path) -> io::Result<AtomicFileWriter> {
+    #[cfg(windows)]
+    {
+        imp::AtomicFileWriter::new(start, dest_path)
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = (start, dest_path);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "atomic_writer is only implemented on Windows in this tree",
+        ))
+    }
+}
+
+/// Atomically writes `contents` to `dest_path`, relative to the directory
+/// `start` is opened on, so a reader never observes a partially-written
+/// file. This is the sandboxed, cross-platform entry point
+/// [`crate::fs::Dir::write_atomic`] is implemented in terms of.
+pub fn write_atomic(start: &fs::File, dest_path: &Path, contents: &[u8]) -> io::Result<()> {
+    #[cfg(windows)]
+    {
+        imp::write_atomic_unchecked(start, dest_path, contents)
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = (start, dest_path, contents);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "write_atomic is only implemented on Windows in this tree",
+        ))
+    }
+}
@@ -0,0 +1,137 @@
+use super::get_path::concatenate;
+use super::open_unchecked::open_unchecked;
+use super::replace_file::replace_file_unchecked;
+use crate::fs::{FollowSymlinks, OpenOptions, OpenUncheckedError};
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::fs;
+
+static UNIQUE: AtomicU32 = AtomicU32::new(0);
+
+/// Picks a temp-file name next to `dest_path`, in the same directory, so
+/// the eventual commit is a same-volume rename. The name is unique per
+/// process and per call, which is enough to avoid collisions between
+/// concurrent writers without needing a source of randomness.
+fn temp_path_for(dest_path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(dest_path.file_name().unwrap_or_default());
+    name.push(format!(
+        ".tmp-{}-{}",
+        std::process::id(),
+        UNIQUE.fetch_add(1, Ordering::Relaxed)
+    ));
+    match dest_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.join(name),
+        _ => PathBuf::from(name),
+    }
+}
+
+fn unchecked_to_io(err: OpenUncheckedError) -> io::Error {
+    match err {
+        OpenUncheckedError::Other(e)
+        | OpenUncheckedError::NotFound(e)
+        | OpenUncheckedError::Symlink(e, _) => e,
+    }
+}
+
+/// A crash-safe writer backing [`crate::fs::Dir::atomic_writer`]: writes go
+/// to a uniquely-named temp file created in the same directory as
+/// `dest_path` through [`open_unchecked`], so the eventual swap is a
+/// same-volume rename. The temp file only replaces the destination once
+/// [`Self::commit`] is called, which flushes and fsyncs it first and then
+/// performs the swap via [`replace_file_unchecked`]. Dropping the writer
+/// without committing discards the temp file instead of leaving it
+/// behind.
+pub struct AtomicFileWriter {
+    start: fs::File,
+    temp_path: PathBuf,
+    dest_path: PathBuf,
+    file: Option<fs::File>,
+    committed: bool,
+}
+
+impl AtomicFileWriter {
+    pub(crate) fn new(start: &fs::File, dest_path: &Path) -> io::Result<Self> {
+        let temp_path = temp_path_for(dest_path);
+
+        let mut opts = OpenOptions::new();
+        opts.create_new(true).write(true).follow(FollowSymlinks::No);
+        let file = open_unchecked(start, &temp_path, &opts).map_err(unchecked_to_io)?;
+
+        Ok(Self {
+            start: start.try_clone()?,
+            temp_path,
+            dest_path: dest_path.to_path_buf(),
+            file: Some(file),
+            committed: false,
+        })
+    }
+
+    /// Flushes and fsyncs the temp file, then atomically swaps it into
+    /// place over the destination. Consumes `self` so a writer can only be
+    /// committed once.
+    pub fn commit(mut self) -> io::Result<()> {
+        let file = self
+            .file
+            .take()
+            .expect("`AtomicFileWriter` has no open temp file");
+        file.sync_all()?;
+        drop(file);
+
+        let full_temp = concatenate(&self.start, &self.temp_path)?;
+        let full_dest = concatenate(&self.start, &self.dest_path)?;
+        replace_file_unchecked(&full_temp, &full_dest)?;
+
+        // The swap already consumed the temp file; mark us committed so
+        // `Drop` doesn't try to remove a path that no longer exists. The
+        // directory handle still needs to be closed normally, so we let
+        // `Drop` run rather than `mem::forget`-ing `self`.
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Write for AtomicFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file
+            .as_mut()
+            .expect("`AtomicFileWriter` has no open temp file")
+            .write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file
+            .as_mut()
+            .expect("`AtomicFileWriter` has no open temp file")
+            .flush()
+    }
+}
+
+impl Drop for AtomicFileWriter {
+    fn drop(&mut self) {
+        self.file.take();
+        if self.committed {
+            return;
+        }
+        // Dropped without a commit: best-effort discard of the abandoned
+        // temp file.
+        if let Ok(full_temp) = concatenate(&self.start, &self.temp_path) {
+            let _ = fs::remove_file(full_temp);
+        }
+    }
+}
+
+/// *Unsandboxed* function backing [`crate::fs::Dir::write_atomic`]: writes
+/// `contents` to a temp file next to `dest_path` and atomically swaps it
+/// into place, so callers never observe a partially-written destination.
+pub(crate) fn write_atomic_unchecked(
+    start: &fs::File,
+    dest_path: &Path,
+    contents: &[u8],
+) -> io::Result<()> {
+    let mut writer = AtomicFileWriter::new(start, dest_path)?;
+    writer.write_all(contents)?;
+    writer.commit()
+}
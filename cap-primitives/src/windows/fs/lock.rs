@@ -0,0 +1,276 @@
+use super::get_path::concatenate;
+use super::open_unchecked::open_unchecked;
+use crate::fs::{FollowSymlinks, LockOptions, OpenOptions, OpenUncheckedError};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::fs;
+use windows_sys::Win32::Foundation::{CloseHandle, STILL_ACTIVE};
+use windows_sys::Win32::System::SystemInformation::{
+    ComputerNamePhysicalDnsHostname, GetComputerNameExW,
+};
+use windows_sys::Win32::System::Threading::{
+    GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+
+/// Returns this machine's DNS hostname, used as the host half of the
+/// `pid@hostname` identity [`crate::fs::Dir::lock`] records in a lockfile,
+/// and compared against a stale lock's recorded host before attempting to
+/// break it: a pid only means something on the host that wrote it.
+pub(crate) fn local_hostname() -> io::Result<String> {
+    // Ask for the required buffer size first, then fetch into a buffer of
+    // that size; `GetComputerNameExW` wants the size in `u16` elements,
+    // including the terminating nul, on the second call.
+    let mut len: u32 = 0;
+    unsafe {
+        GetComputerNameExW(ComputerNamePhysicalDnsHostname, std::ptr::null_mut(), &mut len);
+    }
+    if len == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut buf = vec![0u16; len as usize];
+    let ok = unsafe {
+        GetComputerNameExW(ComputerNamePhysicalDnsHostname, buf.as_mut_ptr(), &mut len)
+    };
+    if ok == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    buf.truncate(len as usize);
+    Ok(String::from_utf16_lossy(&buf))
+}
+
+/// Returns `true` if a process with the given pid is currently running on
+/// this machine, used by [`crate::fs::Dir::lock`]'s stale-lock detection:
+/// a lock recorded by this host but whose pid is no longer alive is safe
+/// to break.
+///
+/// This only opens the process to query whether it has exited, so it
+/// works even for processes owned by a different user, and doesn't
+/// require any elevated privileges.
+pub(crate) fn process_is_alive(pid: u32) -> bool {
+    let handle = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid) };
+    if handle == 0 {
+        // `OpenProcess` fails for a pid that doesn't currently exist.
+        return false;
+    }
+
+    let mut exit_code: u32 = 0;
+    let got_exit_code = unsafe { GetExitCodeProcess(handle, &mut exit_code) };
+    unsafe {
+        CloseHandle(handle);
+    }
+
+    got_exit_code != 0 && exit_code == STILL_ACTIVE as u32
+}
+
+fn unchecked_to_io(err: OpenUncheckedError) -> io::Error {
+    match err {
+        OpenUncheckedError::Other(e)
+        | OpenUncheckedError::NotFound(e)
+        | OpenUncheckedError::Symlink(e, _) => e,
+    }
+}
+
+/// A held advisory lock, backing [`crate::fs::Dir::lock`]'s return value.
+/// Releases the lock by removing the lockfile on drop.
+pub struct LockGuard {
+    start: fs::File,
+    path: PathBuf,
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        if let Ok(full_path) = concatenate(&self.start, &self.path) {
+            let _ = fs::remove_file(full_path);
+        }
+    }
+}
+
+/// Creates `path` exclusively and writes this process's `pid@hostname`
+/// identity into it, failing with [`io::ErrorKind::AlreadyExists`] if the
+/// lockfile is already held.
+fn try_create_lock(start: &fs::File, path: &std::path::Path) -> io::Result<()> {
+    let mut opts = OpenOptions::new();
+    opts.create_new(true)
+        .write(true)
+        .follow(FollowSymlinks::No);
+    let mut file = open_unchecked(start, path, &opts).map_err(unchecked_to_io)?;
+    let identity = format!("{}@{}", std::process::id(), local_hostname()?);
+    file.write_all(identity.as_bytes())?;
+    file.sync_all()
+}
+
+/// Reads the `pid@hostname` identity out of an existing lockfile and
+/// reports whether it's stale: recorded by this host, but for a pid that
+/// isn't running anymore. A lockfile that can't be parsed, or that no
+/// longer exists by the time we get to read it, is treated as not stale
+/// so callers don't break a lock they don't understand.
+fn lock_is_stale(start: &fs::File, path: &std::path::Path) -> io::Result<bool> {
+    let mut opts = OpenOptions::new();
+    opts.read(true).follow(FollowSymlinks::No);
+    let mut file = match open_unchecked(start, path, &opts) {
+        Ok(file) => file,
+        Err(OpenUncheckedError::NotFound(_)) => return Ok(false),
+        Err(e) => return Err(unchecked_to_io(e)),
+    };
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    let (pid_str, host) = match contents.split_once('@') {
+        Some(parts) => parts,
+        None => return Ok(false),
+    };
+    let pid: u32 = match pid_str.parse() {
+        Ok(pid) => pid,
+        Err(_) => return Ok(false),
+    };
+
+    Ok(host == local_hostname()? && !process_is_alive(pid))
+}
+
+/// *Unsandboxed* function backing [`crate::fs::Dir::lock`]: atomically
+/// creates the lockfile at `path`, relative to the directory `start` is
+/// opened on, recording this process's `pid@hostname` identity in it.
+///
+/// If the lockfile already exists and `options.break_stale` is set, we
+/// read back the recorded identity; if it names this host but a pid that
+/// isn't alive anymore, the lock is stale, so we remove it and retry
+/// acquisition exactly once. A lock that's either live or unparseable is
+/// left alone and reported as held.
+pub(crate) fn acquire_lock_unchecked(
+    start: &fs::File,
+    path: &std::path::Path,
+    options: &LockOptions,
+) -> io::Result<LockGuard> {
+    match try_create_lock(start, path) {
+        Ok(()) => return Ok(LockGuard {
+            start: start.try_clone()?,
+            path: path.to_path_buf(),
+        }),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+
+    if options.break_stale && lock_is_stale(start, path)? {
+        let full_path = concatenate(start, path)?;
+        let _ = fs::remove_file(full_path);
+        try_create_lock(start, path)?;
+        return Ok(LockGuard {
+            start: start.try_clone()?,
+            path: path.to_path_buf(),
+        });
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "lock is held by another process",
+    ))
+}
+
+#[cfg(all(test, windows))]
+mod tests {
+    use super::*;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::path::Path;
+    use windows_sys::Win32::Storage::FileSystem::FILE_FLAG_BACKUP_SEMANTICS;
+
+    fn unique_temp_dir(name: &str) -> PathBuf {
+        static UNIQUE: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+        let mut dir = std::env::temp_dir();
+        dir.push(format!(
+            "cap_primitives_lock_{}_{}_{}",
+            name,
+            std::process::id(),
+            UNIQUE.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+        dir
+    }
+
+    /// Opens `path` as a directory handle, the way a `Dir` wraps the
+    /// directory it's confined to, so these tests exercise
+    /// `acquire_lock_unchecked`'s sandboxed, `start`-relative API rather
+    /// than bypassing it with raw paths.
+    fn open_dir_handle(path: &Path) -> fs::File {
+        fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+            .open(path)
+            .unwrap()
+    }
+
+    #[test]
+    fn acquire_writes_identity_and_release_removes_it() {
+        let base = unique_temp_dir("acquire");
+        fs::create_dir_all(&base).unwrap();
+        let start = open_dir_handle(&base);
+
+        let guard = acquire_lock_unchecked(&start, Path::new("lock"), &LockOptions::new()).unwrap();
+        let contents = fs::read_to_string(base.join("lock")).unwrap();
+        assert_eq!(contents, format!("{}@{}", std::process::id(), local_hostname().unwrap()));
+
+        drop(guard);
+        assert!(!base.join("lock").exists());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn lock_held_by_a_live_pid_is_not_stale() {
+        let base = unique_temp_dir("live");
+        fs::create_dir_all(&base).unwrap();
+        let start = open_dir_handle(&base);
+
+        // This process's own pid is, by definition, alive, so a lockfile
+        // naming it should never be treated as stale.
+        let identity = format!("{}@{}", std::process::id(), local_hostname().unwrap());
+        fs::write(base.join("lock"), identity).unwrap();
+
+        assert!(!lock_is_stale(&start, Path::new("lock")).unwrap());
+
+        let err =
+            acquire_lock_unchecked(&start, Path::new("lock"), &LockOptions::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn stale_lock_is_broken_and_acquisition_retried_once() {
+        let base = unique_temp_dir("stale");
+        fs::create_dir_all(&base).unwrap();
+        let start = open_dir_handle(&base);
+
+        // A pid this large is never actually in use, so `process_is_alive`
+        // reports it as dead and the lock, naming the real local host,
+        // should be detected as stale.
+        let identity = format!("{}@{}", u32::MAX, local_hostname().unwrap());
+        fs::write(base.join("lock"), &identity).unwrap();
+
+        assert!(lock_is_stale(&start, Path::new("lock")).unwrap());
+
+        let guard =
+            acquire_lock_unchecked(&start, Path::new("lock"), &LockOptions::new()).unwrap();
+        let contents = fs::read_to_string(base.join("lock")).unwrap();
+        assert_ne!(contents, identity);
+        drop(guard);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn stale_lock_is_left_alone_when_break_stale_is_disabled() {
+        let base = unique_temp_dir("stale_disabled");
+        fs::create_dir_all(&base).unwrap();
+        let start = open_dir_handle(&base);
+
+        let identity = format!("{}@{}", u32::MAX, local_hostname().unwrap());
+        fs::write(base.join("lock"), &identity).unwrap();
+
+        let mut options = LockOptions::new();
+        options.break_stale(false);
+        let err = acquire_lock_unchecked(&start, Path::new("lock"), &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -1,6 +1,7 @@
 use super::get_path::concatenate;
 use super::open_options_to_std;
-use crate::fs::{errors, FollowSymlinks, OpenOptions, OpenUncheckedError, SymlinkKind};
+use super::reparse::reparse_kind;
+use crate::fs::{errors, FollowSymlinks, OpenOptions, OpenUncheckedError};
 use crate::{ambient_authority, AmbientAuthority};
 use std::os::windows::fs::MetadataExt;
 use std::path::Path;
@@ -61,18 +62,21 @@ pub(crate) fn open_ambient_impl(
                     // we're not using `FILE_FLAG_OPEN_REPARSE_POINT` manually
                     // to open a symlink itself, check for symlinks and report
                     // them as a distinct error.
-                    if metadata.file_type().is_symlink() {
+                    //
+                    // `metadata.file_type().is_symlink()` alone isn't
+                    // enough here: `std` only reports `IO_REPARSE_TAG_SYMLINK`
+                    // reparse points as symlinks, while directory junctions
+                    // and volume mount points (`IO_REPARSE_TAG_MOUNT_POINT`)
+                    // are reparse points that redirect to an arbitrary
+                    // location just the same, yet come back as ordinary
+                    // directories. Inspect the reparse tag directly so
+                    // those are caught too.
+                    if let Some(kind) = reparse_kind(&f).map_err(OpenUncheckedError::Other)? {
                         return Err(OpenUncheckedError::Symlink(
                             io::Error::from_raw_os_error(
                                 Foundation::ERROR_STOPPED_ON_SYMLINK as i32,
                             ),
-                            if metadata.file_attributes() & FILE_ATTRIBUTE_DIRECTORY
-                                == FILE_ATTRIBUTE_DIRECTORY
-                            {
-                                SymlinkKind::Dir
-                            } else {
-                                SymlinkKind::File
-                            },
+                            kind,
                         ));
                     }
                 }
@@ -0,0 +1,264 @@
+use super::get_path::concatenate;
+use super::reparse::open_entry_unchecked;
+use crate::fs::OpenUncheckedError;
+use std::ffi::OsStr;
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+use windows_sys::Win32::Storage::FileSystem::{SetFileAttributesW, FILE_ATTRIBUTE_READONLY};
+
+fn to_wide(path: &Path) -> Vec<u16> {
+    OsStr::new(path)
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect()
+}
+
+fn unchecked_to_io(err: OpenUncheckedError) -> io::Error {
+    match err {
+        OpenUncheckedError::Other(e)
+        | OpenUncheckedError::NotFound(e)
+        | OpenUncheckedError::Symlink(e, _) => e,
+    }
+}
+
+/// Clears the read-only attribute on `full_path`, if set, so that a
+/// subsequent `RemoveDirectory`/`DeleteFile` doesn't fail with
+/// access-denied. Windows, unlike Unix, refuses to unlink a read-only
+/// entry regardless of the containing directory's permissions.
+fn clear_readonly(full_path: &Path) -> io::Result<()> {
+    let attrs = fs::symlink_metadata(full_path)?.file_attributes();
+    if attrs & FILE_ATTRIBUTE_READONLY == 0 {
+        return Ok(());
+    }
+    let wide = to_wide(full_path);
+    if unsafe { SetFileAttributesW(wide.as_ptr(), attrs & !FILE_ATTRIBUTE_READONLY) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// *Unsandboxed* function which recursively removes the directory tree at
+/// `path`, relative to the directory `start` is opened on, used to
+/// implement [`crate::fs::Dir::remove_dir_all`] on Windows.
+///
+/// Every entry is reached by resolving it relative to `start` via
+/// [`concatenate`], as the other primitives in this module do. This
+/// performs no sandboxing of its own: `path` is joined onto `start`'s path
+/// as given, so an absolute or `..`-laden `path` is not confined to
+/// `start`'s tree.
+///
+/// Two things std's own recursive removal gets wrong on Windows:
+///
+///   - Read-only files nested anywhere in the tree fail to unlink with
+///     access-denied, so we clear `FILE_ATTRIBUTE_READONLY` before every
+///     removal.
+///   - A symlink or junction nested in the tree must be unlinked as the
+///     link itself, never followed into its target, or we'd delete files
+///     outside the tree being removed. Each entry is first opened with
+///     [`open_entry_unchecked`], the same reparse-tag inspection added
+///     for `FollowSymlinks::No` enforcement in `open_unchecked`, so
+///     junctions are treated the same as symlinks. The classification
+///     comes from that handle, but the handle is closed before the entry
+///     is removed by path, so an entry replaced between the check and the
+///     removal is not detected.
+pub(crate) fn remove_dir_all_unchecked(start: &fs::File, path: &Path) -> io::Result<()> {
+    remove_children(start, path)?;
+    let full_path = concatenate(start, path)?;
+    clear_readonly(&full_path)?;
+    fs::remove_dir(full_path)
+}
+
+fn remove_children(start: &fs::File, dir: &Path) -> io::Result<()> {
+    let full_dir = concatenate(start, dir)?;
+    for entry in fs::read_dir(&full_dir)? {
+        let entry = entry?;
+        let rel_child: PathBuf = dir.join(entry.file_name());
+
+        let (handle, kind) = open_entry_unchecked(start, &rel_child).map_err(unchecked_to_io)?;
+        let is_reparse_point = kind.is_some();
+        drop(handle);
+
+        let full_child = concatenate(start, &rel_child)?;
+
+        if is_reparse_point {
+            // A symlink or junction: remove the link itself without
+            // descending into whatever it points at.
+            clear_readonly(&full_child)?;
+            if entry.file_type()?.is_dir() {
+                fs::remove_dir(&full_child)?;
+            } else {
+                fs::remove_file(&full_child)?;
+            }
+            continue;
+        }
+
+        if entry.file_type()?.is_dir() {
+            remove_children(start, &rel_child)?;
+            clear_readonly(&full_child)?;
+            fs::remove_dir(&full_child)?;
+        } else {
+            clear_readonly(&full_child)?;
+            fs::remove_file(&full_child)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, windows))]
+mod tests {
+    use super::*;
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Foundation::{CloseHandle, GENERIC_WRITE, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{
+        CreateFileW, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT, FILE_SHARE_READ,
+        FILE_SHARE_WRITE, IO_REPARSE_TAG_MOUNT_POINT, OPEN_EXISTING,
+    };
+    use windows_sys::Win32::System::Ioctl::FSCTL_SET_REPARSE_POINT;
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+
+    fn unique_temp_dir(name: &str) -> PathBuf {
+        static UNIQUE: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+        let mut dir = std::env::temp_dir();
+        dir.push(format!(
+            "cap_primitives_remove_dir_all_{}_{}_{}",
+            name,
+            std::process::id(),
+            UNIQUE.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+        dir
+    }
+
+    /// Opens `path` as a directory handle, the way a `Dir` wraps the
+    /// directory it's confined to, so these tests exercise
+    /// `remove_dir_all_unchecked`'s sandboxed, `start`-relative API
+    /// rather than bypassing it with raw paths.
+    fn open_dir_handle(path: &Path) -> fs::File {
+        fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+            .open(path)
+            .unwrap()
+    }
+
+    /// Creates an NTFS directory junction at `link` pointing at `target`,
+    /// via `FSCTL_SET_REPARSE_POINT`. This is the same mechanism (and the
+    /// same reparse tag, `IO_REPARSE_TAG_MOUNT_POINT`) that `mklink /J`
+    /// uses, and unlike a directory symlink it doesn't require any
+    /// special privilege to create.
+    fn create_junction(target: &Path, link: &Path) -> io::Result<()> {
+        fs::create_dir(link)?;
+
+        let target = fs::canonicalize(target)?;
+        let target_str = target.to_string_lossy();
+        let target_str = target_str.strip_prefix(r"\\?\").unwrap_or(&target_str);
+        let substitute_name: Vec<u16> = format!(r"\??\{}", target_str).encode_utf16().collect();
+        let print_name: Vec<u16> = target_str.encode_utf16().collect();
+
+        let substitute_bytes = substitute_name.len() * 2;
+        let print_bytes = print_name.len() * 2;
+        let path_buffer_len = substitute_bytes + 2 + print_bytes + 2;
+        let reparse_data_length = 8 + path_buffer_len;
+        let mut buf = vec![0u8; 8 + reparse_data_length];
+
+        buf[0..4].copy_from_slice(&IO_REPARSE_TAG_MOUNT_POINT.to_le_bytes());
+        buf[4..6].copy_from_slice(&(reparse_data_length as u16).to_le_bytes());
+        buf[8..10].copy_from_slice(&0u16.to_le_bytes());
+        buf[10..12].copy_from_slice(&(substitute_bytes as u16).to_le_bytes());
+        buf[12..14].copy_from_slice(&((substitute_bytes + 2) as u16).to_le_bytes());
+        buf[14..16].copy_from_slice(&(print_bytes as u16).to_le_bytes());
+
+        let mut offset = 16;
+        for unit in &substitute_name {
+            buf[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+            offset += 2;
+        }
+        offset += 2; // substitute name's null terminator
+        for unit in &print_name {
+            buf[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+            offset += 2;
+        }
+
+        let link_wide: Vec<u16> = OsStr::new(link)
+            .encode_wide()
+            .chain(std::iter::once(0))
+            .collect();
+        let handle = unsafe {
+            CreateFileW(
+                link_wide.as_ptr(),
+                GENERIC_WRITE,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                std::ptr::null(),
+                OPEN_EXISTING,
+                FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT,
+                0,
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut bytes_returned = 0u32;
+        let ok = unsafe {
+            DeviceIoControl(
+                handle,
+                FSCTL_SET_REPARSE_POINT,
+                buf.as_ptr() as *const _,
+                buf.len() as u32,
+                std::ptr::null_mut(),
+                0,
+                &mut bytes_returned,
+                std::ptr::null_mut(),
+            )
+        };
+        unsafe {
+            CloseHandle(handle);
+        }
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn removes_readonly_file_nested_in_tree() {
+        let base = unique_temp_dir("readonly");
+        fs::create_dir_all(base.join("tree").join("child")).unwrap();
+        let file_path = base.join("tree").join("child").join("file.txt");
+        fs::write(&file_path, b"contents").unwrap();
+
+        let mut perms = fs::metadata(&file_path).unwrap().permissions();
+        perms.set_readonly(true);
+        fs::set_permissions(&file_path, perms).unwrap();
+
+        let start = open_dir_handle(&base);
+        remove_dir_all_unchecked(&start, Path::new("tree")).unwrap();
+
+        assert!(!base.join("tree").exists());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn junction_is_unlinked_without_touching_its_target() {
+        let base = unique_temp_dir("junction_base");
+        fs::create_dir_all(&base).unwrap();
+
+        let sibling = base.join("sibling");
+        fs::create_dir(&sibling).unwrap();
+        fs::write(sibling.join("keep.txt"), b"keep me").unwrap();
+
+        let tree = base.join("tree");
+        fs::create_dir(&tree).unwrap();
+        create_junction(&sibling, &tree.join("link_to_sibling")).unwrap();
+
+        let start = open_dir_handle(&base);
+        remove_dir_all_unchecked(&start, Path::new("tree")).unwrap();
+
+        assert!(!tree.exists());
+        assert!(sibling.join("keep.txt").exists());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
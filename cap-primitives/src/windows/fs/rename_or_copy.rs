@@ -0,0 +1,171 @@
+use super::get_path::concatenate;
+use super::open_unchecked::open_unchecked;
+use super::remove_dir_all::remove_dir_all_unchecked;
+use super::reparse::open_entry_unchecked;
+use crate::fs::{FollowSymlinks, OpenOptions, OpenUncheckedError};
+use std::ffi::OsStr;
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+use windows_sys::Win32::Foundation::ERROR_NOT_SAME_DEVICE;
+use windows_sys::Win32::Storage::FileSystem::{
+    MoveFileExW, FILE_ATTRIBUTE_DIRECTORY, MOVEFILE_REPLACE_EXISTING,
+};
+
+fn to_wide(path: &Path) -> Vec<u16> {
+    OsStr::new(path)
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect()
+}
+
+fn unchecked_to_io(err: OpenUncheckedError) -> io::Error {
+    match err {
+        OpenUncheckedError::Other(e)
+        | OpenUncheckedError::NotFound(e)
+        | OpenUncheckedError::Symlink(e, _) => e,
+    }
+}
+
+/// Returns `true` if `err` is the OS reporting that a rename failed because
+/// the source and destination live on different volumes, in which case
+/// [`crate::fs::Dir::rename_or_copy`] falls back to a copy-then-remove.
+pub(crate) fn is_cross_device_error(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(ERROR_NOT_SAME_DEVICE as i32)
+}
+
+/// *Unsandboxed* function which attempts an atomic rename from `old_path`
+/// to `new_path`, both already resolved to full paths by the caller.
+fn rename_unchecked(old_path: &Path, new_path: &Path) -> io::Result<()> {
+    let old_wide = to_wide(old_path);
+    let new_wide = to_wide(new_path);
+    let renamed =
+        unsafe { MoveFileExW(old_wide.as_ptr(), new_wide.as_ptr(), MOVEFILE_REPLACE_EXISTING) };
+    if renamed == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Copies the single file at `old_path` to `new_path`, both relative to
+/// `old_start`/`new_start` respectively, as the per-file step of the
+/// cross-device rename fallback. Both ends are opened through
+/// [`open_unchecked`], so neither side of the copy can be redirected
+/// outside its directory tree by a symlink or junction. If the copy fails
+/// partway through, the partially-written destination is removed so
+/// callers never observe a half-copied file.
+fn copy_file_unchecked(
+    old_start: &fs::File,
+    old_path: &Path,
+    new_start: &fs::File,
+    new_path: &Path,
+) -> io::Result<()> {
+    let mut read_opts = OpenOptions::new();
+    read_opts.read(true).follow(FollowSymlinks::No);
+    let mut src = open_unchecked(old_start, old_path, &read_opts).map_err(unchecked_to_io)?;
+
+    let mut write_opts = OpenOptions::new();
+    write_opts
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .follow(FollowSymlinks::No);
+    let mut dst = open_unchecked(new_start, new_path, &write_opts).map_err(unchecked_to_io)?;
+
+    if let Err(e) = io::copy(&mut src, &mut dst) {
+        drop(dst);
+        let full_new = concatenate(new_start, new_path)?;
+        let _ = fs::remove_file(full_new);
+        return Err(e);
+    }
+    dst.sync_all()
+}
+
+/// *Unsandboxed* function backing [`crate::fs::Dir::rename_or_copy`]:
+/// attempts an atomic rename of `old_path` (relative to `old_start`) onto
+/// `new_path` (relative to `new_start`) first and, only when that fails
+/// with [`is_cross_device_error`], falls back to a recursive copy
+/// followed by removing the source. Any other rename error is returned
+/// as-is without attempting the fallback.
+pub(crate) fn rename_or_copy_unchecked(
+    old_start: &fs::File,
+    old_path: &Path,
+    new_start: &fs::File,
+    new_path: &Path,
+) -> io::Result<()> {
+    let full_old = concatenate(old_start, old_path)?;
+    let full_new = concatenate(new_start, new_path)?;
+
+    match rename_unchecked(&full_old, &full_new) {
+        Ok(()) => Ok(()),
+        Err(e) if is_cross_device_error(&e) => {
+            copy_recursive(old_start, old_path, new_start, new_path)?;
+            remove_source(old_start, old_path)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Copies `old_path` (relative to `old_start`) onto `new_path` (relative
+/// to `new_start`), recursing into directories. Every entry is opened
+/// with [`open_entry_unchecked`] before being acted on, so a symlink or
+/// junction nested anywhere in the source tree is detected from the
+/// handle we already have open, rather than followed by a later
+/// `fs::read_dir`/`fs::copy` call that re-resolves its path; since this
+/// fallback has no sandboxed way to recreate a link pointing outside its
+/// own directory, it refuses to copy one rather than silently
+/// dereferencing into whatever it points at. If a directory copy fails
+/// partway through, the partially-copied destination directory is
+/// removed so callers never observe a half-copied tree.
+fn copy_recursive(
+    old_start: &fs::File,
+    old_path: &Path,
+    new_start: &fs::File,
+    new_path: &Path,
+) -> io::Result<()> {
+    let (entry, kind) = open_entry_unchecked(old_start, old_path).map_err(unchecked_to_io)?;
+    if kind.is_some() {
+        drop(entry);
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "refusing to copy a symlink or junction across devices",
+        ));
+    }
+    let is_dir = entry.metadata()?.file_attributes() & FILE_ATTRIBUTE_DIRECTORY != 0;
+    drop(entry);
+
+    if !is_dir {
+        return copy_file_unchecked(old_start, old_path, new_start, new_path);
+    }
+
+    let full_new = concatenate(new_start, new_path)?;
+    fs::create_dir(&full_new)?;
+
+    let full_old = concatenate(old_start, old_path)?;
+    for entry in fs::read_dir(&full_old)? {
+        let entry = entry?;
+        let old_child: PathBuf = old_path.join(entry.file_name());
+        let new_child: PathBuf = new_path.join(entry.file_name());
+        if let Err(e) = copy_recursive(old_start, &old_child, new_start, &new_child) {
+            let _ = remove_dir_all_unchecked(new_start, new_path);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Removes the rename's source after a successful copy, relative to
+/// `start`, recursing for a directory via [`remove_dir_all_unchecked`].
+fn remove_source(start: &fs::File, path: &Path) -> io::Result<()> {
+    let (entry, _) = open_entry_unchecked(start, path).map_err(unchecked_to_io)?;
+    let is_dir = entry.metadata()?.file_attributes() & FILE_ATTRIBUTE_DIRECTORY != 0;
+    drop(entry);
+
+    if is_dir {
+        remove_dir_all_unchecked(start, path)
+    } else {
+        let full_path = concatenate(start, path)?;
+        fs::remove_file(full_path)
+    }
+}
@@ -0,0 +1,242 @@
+use super::open_unchecked::open_unchecked;
+use crate::fs::{FollowSymlinks, OpenOptions, OpenUncheckedError, SymlinkKind};
+use std::os::windows::io::AsRawHandle;
+use std::path::Path;
+use std::{fs, io};
+use windows_sys::Win32::Storage::FileSystem::{
+    FileAttributeTagInfo, GetFileInformationByHandleEx, FILE_ATTRIBUTE_DIRECTORY,
+    FILE_ATTRIBUTE_REPARSE_POINT, FILE_ATTRIBUTE_TAG_INFO, FILE_FLAG_OPEN_REPARSE_POINT,
+    IO_REPARSE_TAG_MOUNT_POINT, IO_REPARSE_TAG_SYMLINK,
+};
+
+/// If the already-open `file` is a reparse point, classify it as a
+/// symlink-like redirect and return the kind of link it is. Returns
+/// `Ok(None)` for anything that isn't a reparse point, including a plain
+/// file or directory.
+///
+/// We distinguish `IO_REPARSE_TAG_SYMLINK` from other reparse tags such as
+/// `IO_REPARSE_TAG_MOUNT_POINT` (directory junctions and volume mount
+/// points), since `std`'s `FileType::is_symlink` only reports `true` for
+/// the former, even though a junction redirects resolution just as a
+/// symlink does.
+///
+/// This reads the tag straight off `file`'s handle via
+/// `GetFileInformationByHandleEx(FileAttributeTagInfo)`, rather than
+/// re-resolving `file`'s path with something like `FindFirstFileW`: the
+/// whole point of this check is race-safe symlink rejection, so the
+/// object being classified must be the same object the caller already has
+/// open, not whatever happens to be at that path by the time we look.
+pub(crate) fn reparse_kind(file: &fs::File) -> io::Result<Option<SymlinkKind>> {
+    let mut info: FILE_ATTRIBUTE_TAG_INFO = unsafe { std::mem::zeroed() };
+    let ok = unsafe {
+        GetFileInformationByHandleEx(
+            file.as_raw_handle() as _,
+            FileAttributeTagInfo,
+            &mut info as *mut FILE_ATTRIBUTE_TAG_INFO as *mut _,
+            std::mem::size_of::<FILE_ATTRIBUTE_TAG_INFO>() as u32,
+        )
+    };
+    if ok == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if info.FileAttributes & FILE_ATTRIBUTE_REPARSE_POINT == 0 {
+        return Ok(None);
+    }
+
+    let is_dir = info.FileAttributes & FILE_ATTRIBUTE_DIRECTORY != 0;
+    Ok(Some(match info.ReparseTag {
+        IO_REPARSE_TAG_SYMLINK => {
+            if is_dir {
+                SymlinkKind::Dir
+            } else {
+                SymlinkKind::File
+            }
+        }
+        IO_REPARSE_TAG_MOUNT_POINT => SymlinkKind::Junction,
+        // Any other redirecting reparse tag (e.g. an app-exec-link, a
+        // cloud-file placeholder, etc.) resolves through the filesystem
+        // the same way a junction does, so treat it as one too.
+        _ => SymlinkKind::Junction,
+    }))
+}
+
+/// Opens `path`, relative to the directory `start` is opened on, without
+/// following a reparse point if `path` names one, and classifies the
+/// result via [`reparse_kind`].
+///
+/// This is the building block callers that walk a directory tree (the
+/// cross-device rename fallback, recursive removal) use to tell a
+/// symlink or junction apart from an ordinary entry *before* deciding
+/// whether to recurse into it, without a separate check-then-open step:
+/// the open itself can't follow the reparse point, and the handle it
+/// returns is exactly what gets classified.
+pub(crate) fn open_entry_unchecked(
+    start: &fs::File,
+    path: &Path,
+) -> Result<(fs::File, Option<SymlinkKind>), OpenUncheckedError> {
+    let mut opts = OpenOptions::new();
+    opts.read(true)
+        .follow(FollowSymlinks::No)
+        .custom_flags(FILE_FLAG_OPEN_REPARSE_POINT);
+    let file = open_unchecked(start, path, &opts)?;
+    let kind = reparse_kind(&file).map_err(OpenUncheckedError::Other)?;
+    Ok((file, kind))
+}
+
+#[cfg(all(test, windows))]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Foundation::{CloseHandle, GENERIC_WRITE, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{
+        CreateFileW, FILE_FLAG_BACKUP_SEMANTICS, FILE_SHARE_READ, FILE_SHARE_WRITE,
+        OPEN_EXISTING,
+    };
+    use windows_sys::Win32::System::Ioctl::FSCTL_SET_REPARSE_POINT;
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+
+    fn unique_temp_dir(name: &str) -> std::path::PathBuf {
+        static UNIQUE: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+        let mut dir = std::env::temp_dir();
+        dir.push(format!(
+            "cap_primitives_reparse_{}_{}_{}",
+            name,
+            std::process::id(),
+            UNIQUE.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+        dir
+    }
+
+    /// Same technique as the junction regression test in
+    /// `remove_dir_all.rs`: sets an `IO_REPARSE_TAG_MOUNT_POINT` reparse
+    /// point via `FSCTL_SET_REPARSE_POINT` directly, since creating a
+    /// junction doesn't require the elevated privilege a directory
+    /// symlink does.
+    fn create_junction(target: &Path, link: &Path) -> io::Result<()> {
+        fs::create_dir(link)?;
+
+        let target = fs::canonicalize(target)?;
+        let target_str = target.to_string_lossy();
+        let target_str = target_str.strip_prefix(r"\\?\").unwrap_or(&target_str);
+        let substitute_name: Vec<u16> = format!(r"\??\{}", target_str).encode_utf16().collect();
+        let print_name: Vec<u16> = target_str.encode_utf16().collect();
+
+        let substitute_bytes = substitute_name.len() * 2;
+        let print_bytes = print_name.len() * 2;
+        let path_buffer_len = substitute_bytes + 2 + print_bytes + 2;
+        let reparse_data_length = 8 + path_buffer_len;
+        let mut buf = vec![0u8; 8 + reparse_data_length];
+
+        buf[0..4].copy_from_slice(&IO_REPARSE_TAG_MOUNT_POINT.to_le_bytes());
+        buf[4..6].copy_from_slice(&(reparse_data_length as u16).to_le_bytes());
+        buf[8..10].copy_from_slice(&0u16.to_le_bytes());
+        buf[10..12].copy_from_slice(&(substitute_bytes as u16).to_le_bytes());
+        buf[12..14].copy_from_slice(&((substitute_bytes + 2) as u16).to_le_bytes());
+        buf[14..16].copy_from_slice(&(print_bytes as u16).to_le_bytes());
+
+        let mut offset = 16;
+        for unit in &substitute_name {
+            buf[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+            offset += 2;
+        }
+        offset += 2; // substitute name's null terminator
+        for unit in &print_name {
+            buf[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+            offset += 2;
+        }
+
+        let link_wide: Vec<u16> = OsStr::new(link)
+            .encode_wide()
+            .chain(std::iter::once(0))
+            .collect();
+        let handle = unsafe {
+            CreateFileW(
+                link_wide.as_ptr(),
+                GENERIC_WRITE,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                std::ptr::null(),
+                OPEN_EXISTING,
+                FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT,
+                0,
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut bytes_returned = 0u32;
+        let ok = unsafe {
+            DeviceIoControl(
+                handle,
+                FSCTL_SET_REPARSE_POINT,
+                buf.as_ptr() as *const _,
+                buf.len() as u32,
+                std::ptr::null_mut(),
+                0,
+                &mut bytes_returned,
+                std::ptr::null_mut(),
+            )
+        };
+        unsafe {
+            CloseHandle(handle);
+        }
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn open_reparse_point(path: &Path) -> io::Result<fs::File> {
+        use std::os::windows::fs::OpenOptionsExt;
+        fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT)
+            .open(path)
+    }
+
+    #[test]
+    fn ordinary_file_is_not_a_reparse_point() {
+        let dir = unique_temp_dir("plain_file");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt");
+        fs::write(&path, b"hello").unwrap();
+
+        let file = fs::File::open(&path).unwrap();
+        assert_eq!(reparse_kind(&file).unwrap(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn directory_symlink_is_classified_as_dir() {
+        let dir = unique_temp_dir("symlink_base");
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("target");
+        fs::create_dir(&target).unwrap();
+        let link = dir.join("link");
+
+        if std::os::windows::fs::symlink_dir(&target, &link).is_ok() {
+            let file = open_reparse_point(&link).unwrap();
+            assert_eq!(reparse_kind(&file).unwrap(), Some(SymlinkKind::Dir));
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn junction_is_classified_as_junction() {
+        let dir = unique_temp_dir("junction_base");
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("target");
+        fs::create_dir(&target).unwrap();
+        let link = dir.join("link");
+        create_junction(&target, &link).unwrap();
+
+        let file = open_reparse_point(&link).unwrap();
+        assert_eq!(reparse_kind(&file).unwrap(), Some(SymlinkKind::Junction));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
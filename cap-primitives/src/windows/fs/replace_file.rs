@@ -0,0 +1,65 @@
+use std::ffi::OsStr;
+use std::io;
+use std::os::windows::ffi::OsStrExt;
+use std::path::Path;
+use windows_sys::Win32::Foundation::ERROR_FILE_NOT_FOUND;
+use windows_sys::Win32::Storage::FileSystem::{
+    MoveFileExW, ReplaceFileW, MOVEFILE_REPLACE_EXISTING, MOVEFILE_WRITE_THROUGH,
+};
+
+fn to_wide(path: &Path) -> Vec<u16> {
+    OsStr::new(path)
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect()
+}
+
+/// *Unsandboxed* function which atomically replaces `dest_path` with
+/// `temp_path`, used to implement [`crate::fs::write_atomic`]'s commit step
+/// once the temp file has been written and flushed.
+///
+/// When `dest_path` already exists we go through `ReplaceFileW`, which
+/// preserves the destination's ACLs, alternate data streams, and other
+/// attributes across the swap. When it doesn't, `ReplaceFileW` fails, so we
+/// fall back to `MoveFileExW` with `MOVEFILE_REPLACE_EXISTING |
+/// MOVEFILE_WRITE_THROUGH`, which is atomic but doesn't carry over any
+/// destination metadata since there is none to carry over.
+///
+/// Both `temp_path` and `dest_path` are expected to already have been
+/// resolved through the sandboxed path-resolution machinery in this crate;
+/// this function itself performs no sandboxing.
+pub(crate) fn replace_file_unchecked(temp_path: &Path, dest_path: &Path) -> io::Result<()> {
+    let temp_wide = to_wide(temp_path);
+    let dest_wide = to_wide(dest_path);
+
+    let replaced = unsafe {
+        ReplaceFileW(
+            dest_wide.as_ptr(),
+            temp_wide.as_ptr(),
+            std::ptr::null(),
+            0,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if replaced != 0 {
+        return Ok(());
+    }
+
+    let err = io::Error::last_os_error();
+    if err.raw_os_error() != Some(ERROR_FILE_NOT_FOUND as i32) {
+        return Err(err);
+    }
+
+    let moved = unsafe {
+        MoveFileExW(
+            temp_wide.as_ptr(),
+            dest_wide.as_ptr(),
+            MOVEFILE_REPLACE_EXISTING | MOVEFILE_WRITE_THROUGH,
+        )
+    };
+    if moved == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}